@@ -9,31 +9,76 @@
 //! active for the duration of the game session. When compiled and placed
 //! alongside other Elden Ring mods (e.g. via `libraryloader`) this code will
 //! automatically intercept events without any additional input from the user.
+//!
+//! The UDP socket is not just a one-way event tap: external tools can send
+//! it `fire <event_name>` or `lua <chunk>` datagrams to drive the game
+//! interactively (see [`Command`]). Since the game thread owns the Lua
+//! state, these requests are queued by a dedicated receiver thread and only
+//! ever executed from inside the existing detours, which already run on the
+//! game thread.
+//!
+//! Setting `trace` in the config additionally installs a `lua_sethook`
+//! callback that reports call/line/count events as they execute, letting
+//! users observe the game's Lua/HKS execution flow rather than only its
+//! fired behaviour events.
+//!
+//! When `stats_interval` is non-zero, every fired event is also timed and
+//! counted per `<character_id>:<event_name>` (see [`EventStats`]); a
+//! background thread periodically publishes the accumulated numbers so
+//! users get a lightweight in-game profiler without an external sampler.
+//!
+//! All of the above goes out through [`HookContext::publish`], which sends
+//! plaintext by default but switches to ChaCha20-Poly1305 when `encrypt`
+//! and `key` are set in the config, so the feed can be relayed off-host
+//! without leaking behaviour events or Lua output.
+//!
+//! `format: "binary"` additionally swaps the legacy `kind:body` strings for
+//! the versioned [`Frame`] layout, so consumers can demux event/debug/trace/
+//! stats/heartbeat messages, detect dropped datagrams via `seq`, and avoid
+//! parsing colons out of names that may themselves contain colons.
+//!
+//! `lua_bindings` lets mod authors expose any number (up to
+//! [`MAX_LUA_BINDINGS`]) of named HKS-visible functions (not just the
+//! original hardcoded `DebugSend`), each publishing under its own
+//! configured tag - see [`lua_binding_call`].
+//!
+//! Every address and struct offset above is specific to a single game
+//! build; rather than hardcoding them, they are described per-version in
+//! `offsets.yaml` next to the DLL (see the [`offsets`] module) and selected
+//! by the detected game version, falling back to the built-in 1.16.1
+//! constants - with a published warning - when no profile matches.
 
 #![allow(non_snake_case)]
 
 use pelite::pe64::Pe;
 use retour::static_detour;
 use serde::Deserialize;
+use binrw::{binrw, BinWrite, NullString};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key};
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
 use std::ffi::c_void;
 use std::ffi::{CStr, CString};
+use std::io::Cursor;
 use std::net::UdpSocket;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use windows::core::PCWSTR;
 use windows::Win32::System::LibraryLoader::{GetModuleFileNameW, GetModuleHandleW};
 
 use eldenring_util::system::wait_for_system_init;
 use shared::program::Program;
 
-// RVAs for 1.16.1
-const PUSHHKSGLOBALS1_RVA: u32 = 0x145ce30;
-const HKS_ADDNAMEDCCLOSURE_RVA: u32 = 0x145d9d0;
-const HKBFIREEVENTHKS_RVA: u32 = 0x145a960;
-const LUA_GETSTRING_RVA: u32 = 0x14e26c0;
-const LUA_GETHKBSELF_RVA: u32 = 0x14522e0;
-const LUA_GETHAVOKSTRUCT_RVA: u32 = 0x1451760;
+mod offsets;
+use offsets::OffsetProfile;
+
+// lua_sethook mask bits, as defined by the Lua 5.1/HKS debug API
+const LUA_MASKCALL: i32 = 1 << 0;
+const LUA_MASKLINE: i32 = 1 << 1;
+const LUA_MASKCOUNT: i32 = 1 << 2;
 
 static_detour! {
     static PushHksGlobals1Hook: unsafe extern "C" fn(usize) -> usize;
@@ -45,6 +90,158 @@ struct Config {
     port: u16,
     chr: String,
     print: bool,
+    /// Opt-in Lua execution tracing via `lua_sethook`. One of `"call"`,
+    /// `"line"` or `"count"`; omit (or leave `None`) to disable tracing.
+    #[serde(default)]
+    trace: Option<String>,
+    /// Instruction interval for `trace: "count"`. Ignored for the other
+    /// trace modes.
+    #[serde(default = "default_trace_count")]
+    trace_count: i32,
+    /// How often (in seconds) to publish accumulated [`EventStats`]. `0`
+    /// disables profiling entirely.
+    #[serde(default)]
+    stats_interval: u64,
+    /// Whether accumulated stats are cleared after each publish, or kept
+    /// running for the whole session.
+    #[serde(default)]
+    stats_reset: bool,
+    /// Encrypt every datagram with ChaCha20-Poly1305 instead of sending it
+    /// as plaintext, so the feed can be relayed off of loopback safely.
+    /// Requires `key`. Defaults to `false` so existing plaintext listeners
+    /// keep working.
+    #[serde(default)]
+    encrypt: bool,
+    /// 32-byte ChaCha20-Poly1305 key, hex-encoded (64 hex chars). Required
+    /// when `encrypt` is set.
+    #[serde(default)]
+    key: String,
+    /// Wire format for published messages: `"text"` keeps the legacy
+    /// `kind:body` strings (default, for existing listeners); `"binary"`
+    /// switches to the versioned [`Frame`] layout.
+    #[serde(default = "default_format")]
+    format: String,
+    /// Named C closures to register in the Lua global namespace. Each
+    /// entry's HKS script calls it with a single string argument, which is
+    /// published tagged with that entry's `tag`. Defaults to a single
+    /// `DebugSend` binding tagged `"debug"`, matching the original
+    /// hardcoded behaviour. With `format: "binary"`, only the tags `debug`,
+    /// `heartbeat`, `trace`, `stats` and `error` get their own frame `kind`;
+    /// any other tag is sent as an event frame and the tag is not carried.
+    #[serde(default = "default_lua_bindings")]
+    lua_bindings: Vec<LuaBinding>,
+}
+
+fn default_format() -> String {
+    "text".to_string()
+}
+
+fn default_lua_bindings() -> Vec<LuaBinding> {
+    vec![LuaBinding {
+        name: "DebugSend".to_string(),
+        tag: "debug".to_string(),
+    }]
+}
+
+/// One entry of `lua_bindings`: the HKS-visible global function name, and
+/// the `publish` tag its calls are reported under.
+#[derive(Deserialize, Debug, Clone)]
+struct LuaBinding {
+    name: String,
+    tag: String,
+}
+
+/// Magic bytes identifying a [`Frame`] datagram ("HKBE").
+const FRAME_MAGIC: u32 = 0x4542_4b48;
+const FRAME_VERSION: u8 = 1;
+
+const FRAME_KIND_EVENT: u8 = 0;
+const FRAME_KIND_DEBUG: u8 = 1;
+const FRAME_KIND_HEARTBEAT: u8 = 2;
+const FRAME_KIND_TRACE: u8 = 3;
+const FRAME_KIND_STATS: u8 = 4;
+const FRAME_KIND_ERROR: u8 = 5;
+
+fn frame_kind(kind: &str) -> u8 {
+    match kind {
+        "debug" => FRAME_KIND_DEBUG,
+        "heartbeat" => FRAME_KIND_HEARTBEAT,
+        "trace" => FRAME_KIND_TRACE,
+        "stats" => FRAME_KIND_STATS,
+        "error" => FRAME_KIND_ERROR,
+        _ => FRAME_KIND_EVENT,
+    }
+}
+
+/// Versioned binary replacement for the ad-hoc `"chr:event"` / `"debug:..."`
+/// strings. `seq` lets consumers detect dropped datagrams and correlate
+/// timing without parsing colons out of names that may themselves contain
+/// colons.
+#[binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+struct Frame {
+    magic: u32,
+    version: u8,
+    kind: u8,
+    seq: u64,
+    timestamp_ms: u64,
+    char_id: NullString,
+    body: NullString,
+}
+
+fn default_trace_count() -> i32 {
+    1000
+}
+
+/// Mirrors the Lua 5.1 `lua_Debug` struct (HKS keeps the same layout). Only
+/// the fields the trace hook reads need accurate offsets; `lua_getinfo` is
+/// responsible for filling them in.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct LuaDebug {
+    event: i32,
+    name: *const i8,
+    namewhat: *const i8,
+    what: *const i8,
+    source: *const i8,
+    currentline: i32,
+    nups: i32,
+    linedefined: i32,
+    lastlinedefined: i32,
+    short_src: [i8; 60],
+    i_ci: i32,
+}
+
+/// Call-count and timing stats accumulated for a single `<character_id>:
+/// <event_name>` key, reported periodically when `stats_interval` is set.
+#[derive(Debug, Clone, Copy, Default)]
+struct EventStats {
+    calls: u64,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl EventStats {
+    fn record(&mut self, elapsed: Duration) {
+        self.calls += 1;
+        self.total += elapsed;
+        if self.calls == 1 {
+            self.min = elapsed;
+            self.max = elapsed;
+        } else {
+            self.min = self.min.min(elapsed);
+            self.max = self.max.max(elapsed);
+        }
+    }
+}
+
+thread_local! {
+    // hkbfireevent_detour can recurse (e.g. item pickups firing additional
+    // events before the outer call returns); only the outermost call's
+    // timing is meaningful, so only it is recorded.
+    static FIRE_EVENT_DEPTH: Cell<u32> = Cell::new(0);
 }
 
 /// Collection of game function pointers resolved from RVAs
@@ -56,37 +253,68 @@ struct GameFunctions {
     lua_getstring_fn: unsafe extern "C" fn(usize, i32, usize) -> *const i8,
     lua_gethkbself_fn: unsafe extern "C" fn(usize) -> usize,
     get_hkbcontext_fn: unsafe extern "C" fn(usize, usize) -> usize,
+    lua_pushstring_fn: unsafe extern "C" fn(usize, usize) -> usize,
+    luaL_loadstring_fn: unsafe extern "C" fn(usize, usize) -> i32,
+    lua_pcall_fn: unsafe extern "C" fn(usize, i32, i32, i32) -> i32,
+    lua_sethook_fn: unsafe extern "C" fn(usize, usize, i32, i32) -> i32,
+    lua_getinfo_fn: unsafe extern "C" fn(usize, usize, *mut LuaDebug) -> i32,
+    /// Offset of the `hkStringPtr` character name field on `hkbCharacter`.
+    hkbcharacter_name_offset: usize,
+    /// Low-bit flag mask stored in a `hkStringPtr`'s pointer field.
+    hkstringptr_flag_mask: usize,
 }
 
 impl GameFunctions {
-    /// Resolve all game function addresses from RVAs
-    unsafe fn resolve(program: &Program) -> Self {
-        let va = program.rva_to_va(PUSHHKSGLOBALS1_RVA).unwrap();
-        let pushhksglobals1_fn = 
+    /// Resolve all game function addresses from the RVAs in `profile`.
+    unsafe fn resolve(program: &Program, profile: &OffsetProfile) -> Self {
+        let va = program.rva_to_va(profile.pushhksglobals1).unwrap();
+        let pushhksglobals1_fn =
             std::mem::transmute::<u64, unsafe extern "C" fn(usize) -> usize>(va);
 
-        let va = program.rva_to_va(HKS_ADDNAMEDCCLOSURE_RVA).unwrap();
-        let hks_addnamedcclosure_fn = 
+        let va = program.rva_to_va(profile.hks_addnamedcclosure).unwrap();
+        let hks_addnamedcclosure_fn =
             std::mem::transmute::<u64, unsafe extern "C" fn(usize, usize, usize) -> usize>(va);
 
-        let va = program.rva_to_va(HKBFIREEVENTHKS_RVA).unwrap();
+        let va = program.rva_to_va(profile.hkbfireeventhks).unwrap();
         let hkb_fire_event_fn =
             std::mem::transmute::<u64, unsafe extern "C" fn(usize) -> usize>(va);
 
-        let va = program.rva_to_va(LUA_GETSTRING_RVA).unwrap();
+        let va = program.rva_to_va(profile.lua_getstring).unwrap();
         let lua_getstring_fn = std::mem::transmute::<
             u64,
             unsafe extern "C" fn(usize, i32, usize) -> *const i8,
         >(va);
 
-        let va = program.rva_to_va(LUA_GETHKBSELF_RVA).unwrap();
+        let va = program.rva_to_va(profile.lua_gethkbself).unwrap();
         let lua_gethkbself_fn =
             std::mem::transmute::<u64, unsafe extern "C" fn(usize) -> usize>(va);
 
-        let va = program.rva_to_va(LUA_GETHAVOKSTRUCT_RVA).unwrap();
+        let va = program.rva_to_va(profile.lua_gethavokstruct).unwrap();
         let get_hkbcontext_fn =
             std::mem::transmute::<u64, unsafe extern "C" fn(usize, usize) -> usize>(va);
 
+        let va = program.rva_to_va(profile.lua_pushstring).unwrap();
+        let lua_pushstring_fn =
+            std::mem::transmute::<u64, unsafe extern "C" fn(usize, usize) -> usize>(va);
+
+        let va = program.rva_to_va(profile.lual_loadstring).unwrap();
+        let luaL_loadstring_fn =
+            std::mem::transmute::<u64, unsafe extern "C" fn(usize, usize) -> i32>(va);
+
+        let va = program.rva_to_va(profile.lua_pcall).unwrap();
+        let lua_pcall_fn =
+            std::mem::transmute::<u64, unsafe extern "C" fn(usize, i32, i32, i32) -> i32>(va);
+
+        let va = program.rva_to_va(profile.lua_sethook).unwrap();
+        let lua_sethook_fn =
+            std::mem::transmute::<u64, unsafe extern "C" fn(usize, usize, i32, i32) -> i32>(va);
+
+        let va = program.rva_to_va(profile.lua_getinfo).unwrap();
+        let lua_getinfo_fn = std::mem::transmute::<
+            u64,
+            unsafe extern "C" fn(usize, usize, *mut LuaDebug) -> i32,
+        >(va);
+
         Self {
             pushhksglobals1_fn,
             hks_addnamedcclosure_fn,
@@ -94,6 +322,41 @@ impl GameFunctions {
             lua_getstring_fn,
             lua_gethkbself_fn,
             get_hkbcontext_fn,
+            lua_pushstring_fn,
+            luaL_loadstring_fn,
+            lua_pcall_fn,
+            lua_sethook_fn,
+            lua_getinfo_fn,
+            hkbcharacter_name_offset: profile.hkbcharacter_name_offset,
+            hkstringptr_flag_mask: profile.hkstringptr_flag_mask,
+        }
+    }
+}
+
+/// A pending action requested over the control socket, waiting to be
+/// executed on the game thread.
+#[derive(Debug, Clone)]
+enum Command {
+    /// Fire a behaviour event by name against the most recently seen
+    /// `hkbCharacter`'s Lua state.
+    FireEvent(String),
+    /// Load and run a chunk of Lua/HKS source in the captured `lua_state`.
+    Lua(String),
+}
+
+impl Command {
+    /// Parse a single control-socket datagram into a [`Command`].
+    ///
+    /// Recognised forms are `fire <event_name>` and `lua <chunk>`; anything
+    /// else (or an empty line) is not a command and is ignored.
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("fire ") {
+            Some(Command::FireEvent(rest.trim().to_string()))
+        } else if let Some(rest) = line.strip_prefix("lua ") {
+            Some(Command::Lua(rest.to_string()))
+        } else {
+            None
         }
     }
 }
@@ -103,63 +366,435 @@ struct HookContext {
     sock: UdpSocket,
     config: Config,
     game_fns: GameFunctions,
+    /// Commands received over the control socket, awaiting execution on the
+    /// game thread. The receiver thread only ever pushes onto this queue;
+    /// the detours drain it.
+    commands: Mutex<VecDeque<Command>>,
+    /// The most recent `lua_state` seen by any detour. The game owns the
+    /// Lua state, so this is only ever read/executed from the game thread.
+    lua_state: Mutex<Option<usize>>,
+    /// Per-event profiling data, keyed by `"<character_id>:<event_str>"`.
+    event_stats: Mutex<HashMap<String, EventStats>>,
+    /// Decoded `key` when `config.encrypt` is set and `key` parses as 32
+    /// bytes of hex; `None` means messages are sent as plaintext.
+    encrypt_key: Option<[u8; 32]>,
+    /// Monotonically increasing counter embedded in the plaintext header of
+    /// every encrypted message, so a relay can detect dropped/replayed
+    /// datagrams.
+    seq_counter: AtomicU64,
+}
+
+/// Parse a 64-character hex string into a 32-byte key. Returns `None` for
+/// anything else (wrong length, non-hex characters).
+fn decode_hex_key(s: &str) -> Option<[u8; 32]> {
+    if s.len() != 64 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let mut key = [0u8; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(key)
+}
+
+impl HookContext {
+    /// Encrypt `payload` with ChaCha20-Poly1305, using a fresh random nonce
+    /// per call. The wire format is `nonce (12 bytes) || ciphertext || tag
+    /// (16 bytes)`, with `seq` prefixed to the plaintext so a relay can
+    /// detect dropped or replayed datagrams.
+    fn encrypt(&self, key: &[u8; 32], seq: u64, payload: &[u8]) -> Option<Vec<u8>> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let mut plaintext = Vec::with_capacity(8 + payload.len());
+        plaintext.extend_from_slice(&seq.to_le_bytes());
+        plaintext.extend_from_slice(payload);
+
+        let ciphertext = cipher.encrypt(&nonce, plaintext.as_slice()).ok()?;
+
+        let mut packet = Vec::with_capacity(nonce.len() + ciphertext.len());
+        packet.extend_from_slice(&nonce);
+        packet.extend_from_slice(&ciphertext);
+        Some(packet)
+    }
+
+    /// Legacy `kind:body` string encoding. `kind` `""` reproduces the
+    /// original `"<character_id>:<event_name>"` messages verbatim, and
+    /// `"heartbeat"` sends its body unprefixed like the original heartbeat.
+    fn encode_text(&self, kind: &str, char_id: &str, body: &str) -> Vec<u8> {
+        let text = if kind.is_empty() || kind == "heartbeat" {
+            if char_id.is_empty() {
+                body.to_string()
+            } else {
+                format!("{}:{}", char_id, body)
+            }
+        } else {
+            format!("{}:{}", kind, body)
+        };
+        text.into_bytes()
+    }
+
+    /// Versioned [`Frame`] binary encoding.
+    fn encode_frame(&self, seq: u64, kind: &str, char_id: &str, body: &str) -> Vec<u8> {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        let frame = Frame {
+            magic: FRAME_MAGIC,
+            version: FRAME_VERSION,
+            kind: frame_kind(kind),
+            seq,
+            timestamp_ms,
+            char_id: NullString::from(char_id.to_string()),
+            body: NullString::from(body.to_string()),
+        };
+
+        let mut buf = Cursor::new(Vec::new());
+        // Frame's layout is fixed; writing into an in-memory Cursor can
+        // only fail on I/O errors, which never happen here.
+        frame.write_le(&mut buf).expect("Frame is always writable");
+        buf.into_inner()
+    }
+
+    /// Send a single message over the UDP socket, transparently encoding it
+    /// as either legacy text or a versioned [`Frame`] (per `config.format`)
+    /// and encrypting it when `encrypt` is configured. `kind` is a short
+    /// tag such as `"debug"` or `"trace"`; pass `""` for raw behaviour
+    /// events, kept for backward compatibility with existing listeners.
+    /// `char_id` may be empty for messages not tied to a character.
+    fn publish(&self, kind: &str, char_id: &str, body: &str) {
+        let seq = self.seq_counter.fetch_add(1, Ordering::Relaxed);
+
+        if self.config.print {
+            let text = self.encode_text(kind, char_id, body);
+            println!("{}", String::from_utf8_lossy(&text));
+        }
+
+        let payload = match self.config.format.as_str() {
+            "binary" => self.encode_frame(seq, kind, char_id, body),
+            _ => self.encode_text(kind, char_id, body),
+        };
+
+        let remote = format!("127.0.0.1:{}", self.config.port);
+
+        match &self.encrypt_key {
+            Some(key) => {
+                if let Some(packet) = self.encrypt(key, seq, &payload) {
+                    let _ = self.sock.send_to(&packet, &remote);
+                }
+            }
+            None => {
+                let _ = self.sock.send_to(&payload, &remote);
+            }
+        }
+    }
 }
 
 static HOOK_CONTEXT: Mutex<Option<HookContext>> = Mutex::new(None);
 
-/// Lua C function that receives a string from Lua and sends it over UDP
-unsafe extern "C" fn send_string_lua(lua_state: usize) -> i32 {
+/// Execute a single [`Command`] against the given `lua_state`. Must only be
+/// called from the game thread, and - like the bottom half of
+/// `hkbfireevent_detour` - only after the `HOOK_CONTEXT` lock has been
+/// released: firing an event or running arbitrary Lua can synchronously
+/// trigger nested calls back into hooked functions (see the comment on
+/// `hkbfireevent_detour`), which would deadlock on that same lock if it
+/// were still held here.
+unsafe fn run_command(game_fns: &GameFunctions, print: bool, lua_state: usize, command: Command) {
+    match command {
+        Command::FireEvent(event_name) => {
+            let Ok(name) = CString::new(event_name.clone()) else {
+                eprintln!(
+                    "[hkb_event_listener] ignoring fire command with embedded NUL: {:?}",
+                    event_name
+                );
+                return;
+            };
+
+            // hkb_fire_event_fn reads the event name argument off the Lua
+            // stack, so push it before calling through. The game thread
+            // owns hkb_fire_event_fn's address itself (HkbFireEventHook
+            // patches it to jump into hkbfireevent_detour once enabled),
+            // so go through the trampoline rather than the raw pointer -
+            // calling it directly would re-enter hkbfireevent_detour.
+            (game_fns.lua_pushstring_fn)(lua_state, name.as_ptr() as usize);
+            HkbFireEventHook.call(lua_state);
+
+            if print {
+                println!("[hkb_event_listener] fired event: {}", event_name);
+            }
+        }
+        Command::Lua(chunk) => {
+            let Ok(source) = CString::new(chunk.clone()) else {
+                eprintln!(
+                    "[hkb_event_listener] ignoring lua command with embedded NUL: {:?}",
+                    chunk
+                );
+                return;
+            };
+
+            let status = (game_fns.luaL_loadstring_fn)(lua_state, source.as_ptr() as usize);
+            if status == 0 {
+                (game_fns.lua_pcall_fn)(lua_state, 0, 0, 0);
+            } else if print {
+                println!("[hkb_event_listener] failed to load Lua chunk: {}", chunk);
+            }
+        }
+    }
+}
+
+/// Pop every command queued by the receiver thread off `context.commands`.
+/// Only dequeues - the caller is responsible for running them (via
+/// [`run_command`]) after releasing the `HOOK_CONTEXT` lock, since executing
+/// a command may recurse back into a hooked function.
+fn dequeue_commands(context: &HookContext) -> VecDeque<Command> {
+    std::mem::take(&mut *context.commands.lock().unwrap())
+}
+
+/// Listen for control messages on a clone of the publish socket and enqueue
+/// them for execution on the game thread. Never touches the game or the Lua
+/// state directly - see [`dequeue_commands`] and [`run_command`].
+fn spawn_command_receiver(sock: UdpSocket) {
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            match sock.recv_from(&mut buf) {
+                Ok((len, _)) => {
+                    let Ok(text) = std::str::from_utf8(&buf[..len]) else {
+                        continue;
+                    };
+
+                    if let Some(command) = Command::parse(text) {
+                        let context_guard = HOOK_CONTEXT.lock().unwrap();
+                        if let Some(context) = context_guard.as_ref() {
+                            context.commands.lock().unwrap().push_back(command);
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("[hkb_event_listener] control socket recv failed: {e}");
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// `lua_Hook` callback installed via `lua_sethook` when `trace` is enabled
+/// in the config. Reports the currently executing function's name, source
+/// and line over the UDP socket so users can observe the game's Lua/HKS
+/// execution flow, not just fired behaviour events.
+unsafe extern "C" fn lua_trace_hook(lua_state: *mut c_void, ar: *mut LuaDebug) {
     let context_guard = HOOK_CONTEXT.lock().unwrap();
     if let Some(context) = context_guard.as_ref() {
-        // Get the string argument from Lua stack at index 1
-        let lua_str_ptr = (context.game_fns.lua_getstring_fn)(lua_state, 1, 0);
-        
-        if !lua_str_ptr.is_null() {
-            if let Ok(message) = CStr::from_ptr(lua_str_ptr).to_str() {
-                let remote = format!("127.0.0.1:{}", context.config.port);
-                let text = format!("debug:{}", message);
-
-                if context.config.print {
-                    println!("{}", text);
-                }
+        let what = CString::new("nSl").unwrap();
+        (context.game_fns.lua_getinfo_fn)(lua_state as usize, what.as_ptr() as usize, ar);
+
+        let name = if (*ar).name.is_null() {
+            "?".to_string()
+        } else {
+            CStr::from_ptr((*ar).name).to_string_lossy().into_owned()
+        };
+        let source = if (*ar).source.is_null() {
+            "?".to_string()
+        } else {
+            CStr::from_ptr((*ar).source).to_string_lossy().into_owned()
+        };
+        let line = (*ar).currentline;
+
+        context.publish("trace", "", &format!("{}:{}:{}", source, line, name));
+    }
+}
 
-                let _ = context.sock.send_to(text.as_bytes(), &remote);
+/// Install the `lua_trace_hook` according to the `trace`/`trace_count`
+/// config fields. No-op when tracing is disabled.
+unsafe fn install_trace_hook(context: &HookContext, lua_state: usize) {
+    let mask = match context.config.trace.as_deref() {
+        Some("call") => LUA_MASKCALL,
+        Some("line") => LUA_MASKLINE,
+        Some("count") => LUA_MASKCOUNT,
+        _ => return,
+    };
+
+    (context.game_fns.lua_sethook_fn)(
+        lua_state,
+        lua_trace_hook as usize,
+        mask,
+        context.config.trace_count,
+    );
+}
+
+/// Serialize the accumulated [`EventStats`] into a `stats:` datagram body.
+/// Format is `<key>=<calls>,<total_ms>,<min_ms>,<max_ms>;...`.
+fn format_stats(stats: &HashMap<String, EventStats>) -> String {
+    let mut body = String::new();
+    for (key, s) in stats {
+        body.push_str(&format!(
+            "{}={},{},{},{};",
+            key,
+            s.calls,
+            s.total.as_secs_f64() * 1000.0,
+            s.min.as_secs_f64() * 1000.0,
+            s.max.as_secs_f64() * 1000.0,
+        ));
+    }
+    body
+}
+
+/// Periodically publish accumulated per-event call-count and timing stats
+/// over the UDP socket. Runs for the lifetime of the session; does nothing
+/// until at least one event has been recorded. Stats cover every
+/// character, not only those matching the configured `chr`.
+fn spawn_stats_publisher(interval: u64, reset: bool) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_secs(interval));
+
+        let context_guard = HOOK_CONTEXT.lock().unwrap();
+        if let Some(context) = context_guard.as_ref() {
+            let mut stats = context.event_stats.lock().unwrap();
+            if stats.is_empty() {
+                continue;
+            }
+
+            context.publish("stats", "", &format_stats(&stats));
+
+            if reset {
+                stats.clear();
+            }
+        }
+    });
+}
+
+/// Upper bound on the number of simultaneously registered `lua_bindings`
+/// entries. `hks_addnamedcclosure_fn` registers a plain C function pointer
+/// with no upvalue to stash a tag in, so each configured binding needs its
+/// own distinct, statically-known function pointer to tell which one fired
+/// - there is no way to recover that from a single shared trampoline (an
+/// unpopulated `lua_Debug` doesn't carry a valid call frame to resolve via
+/// `lua_getinfo`). `lua_binding_trampoline!` below generates one such
+/// function per slot; bindings beyond this count are dropped with a
+/// warning in [`pushhksglobals1_detour`].
+const MAX_LUA_BINDINGS: usize = 8;
+
+/// Shared body behind every generated `lua_binding_trampoline_N` function:
+/// look up the binding registered at `index` and publish its string
+/// argument under that binding's tag.
+unsafe fn lua_binding_call(index: usize, lua_state: usize) -> i32 {
+    let context_guard = HOOK_CONTEXT.lock().unwrap();
+    if let Some(context) = context_guard.as_ref() {
+        if let Some(binding) = context.config.lua_bindings.get(index) {
+            let lua_str_ptr = (context.game_fns.lua_getstring_fn)(lua_state, 1, 0);
+            if !lua_str_ptr.is_null() {
+                if let Ok(message) = CStr::from_ptr(lua_str_ptr).to_str() {
+                    context.publish(&binding.tag, "", message);
+                }
             }
         }
     }
-    
+
     // Return 0 to indicate no return values pushed to Lua stack
     0
 }
 
+/// Defines `unsafe extern "C" fn $name(lua_state: usize) -> i32`, one of the
+/// `MAX_LUA_BINDINGS` distinct function pointers `hks_addnamedcclosure_fn`
+/// can be handed, each hardcoded to its own slot in `LUA_BINDING_TRAMPOLINES`.
+macro_rules! lua_binding_trampoline {
+    ($name:ident, $index:expr) => {
+        unsafe extern "C" fn $name(lua_state: usize) -> i32 {
+            lua_binding_call($index, lua_state)
+        }
+    };
+}
+
+lua_binding_trampoline!(lua_binding_trampoline_0, 0);
+lua_binding_trampoline!(lua_binding_trampoline_1, 1);
+lua_binding_trampoline!(lua_binding_trampoline_2, 2);
+lua_binding_trampoline!(lua_binding_trampoline_3, 3);
+lua_binding_trampoline!(lua_binding_trampoline_4, 4);
+lua_binding_trampoline!(lua_binding_trampoline_5, 5);
+lua_binding_trampoline!(lua_binding_trampoline_6, 6);
+lua_binding_trampoline!(lua_binding_trampoline_7, 7);
+
+const LUA_BINDING_TRAMPOLINES: [unsafe extern "C" fn(usize) -> i32; MAX_LUA_BINDINGS] = [
+    lua_binding_trampoline_0,
+    lua_binding_trampoline_1,
+    lua_binding_trampoline_2,
+    lua_binding_trampoline_3,
+    lua_binding_trampoline_4,
+    lua_binding_trampoline_5,
+    lua_binding_trampoline_6,
+    lua_binding_trampoline_7,
+];
+
 /// Detour function for PushHksGlobals1 - registers custom Lua functions
 unsafe fn pushhksglobals1_detour(lua_state: usize) -> usize {
-    {
+    // Lock inside limited scope and unlock before running any queued
+    // commands: like hkbfireevent_detour below, firing an event or running
+    // Lua can recurse back into a hooked function, which would deadlock on
+    // HOOK_CONTEXT if it were still held here.
+    let pending = {
         let context_guard = HOOK_CONTEXT.lock().unwrap();
-        if let Some(context) = context_guard.as_ref() {
-            // Register our custom function in Lua's global namespace
-            // Function name must be null-terminated C string
-            let function_name = CString::new("DebugSend").unwrap();
-            let _ = (context.game_fns.hks_addnamedcclosure_fn)(
-                lua_state,
-                function_name.as_ptr() as usize,
-                send_string_lua as usize
-            );
+        context_guard.as_ref().map(|context| {
+            // Register every configured binding under its own dedicated
+            // trampoline slot so lua_binding_call can tell them apart by
+            // index. Function names must be null-terminated C strings.
+            for (index, binding) in context.config.lua_bindings.iter().enumerate() {
+                if index >= MAX_LUA_BINDINGS {
+                    eprintln!(
+                        "[hkb_event_listener] more than {} lua_bindings configured; ignoring \"{}\" (tag \"{}\")",
+                        MAX_LUA_BINDINGS, binding.name, binding.tag
+                    );
+                    continue;
+                }
+
+                let function_name = CString::new(binding.name.as_str()).unwrap();
+                let _ = (context.game_fns.hks_addnamedcclosure_fn)(
+                    lua_state,
+                    function_name.as_ptr() as usize,
+                    LUA_BINDING_TRAMPOLINES[index] as usize,
+                );
+            }
+
+            *context.lua_state.lock().unwrap() = Some(lua_state);
+            install_trace_hook(context, lua_state);
+
+            (dequeue_commands(context), context.game_fns, context.config.print)
+        })
+    };
+
+    if let Some((commands, game_fns, print)) = pending {
+        for command in commands {
+            run_command(&game_fns, print, lua_state, command);
         }
     }
-    
+
     // Call the original function to continue normal initialization
     PushHksGlobals1Hook.call(lua_state)
 }
 
 /// Detour function for HkbFireEvent - intercepts behavior events and forwards them over UDP
 unsafe fn hkbfireevent_detour(lua_state: usize) -> usize {
+    // Key for the event being fired on this call, computed below so it is
+    // available after the lock is released to record profiling data.
+    let mut event_key: Option<String> = None;
+
+    // Commands queued by the receiver thread since the last drain. Only
+    // dequeued here - run below, once the lock has been released (see the
+    // comment on that lock scope).
+    let mut pending_commands = None;
+
     // Lock inside limited scope, unlock before calling the original function.
-    // Some game events like item pickups may lead to additional calls to hkbfireevent 
+    // Some game events like item pickups may lead to additional calls to hkbfireevent
     // before this function returns, which would lead to a deadlock!
     {
         let context_guard = HOOK_CONTEXT.lock().unwrap();
         if let Some(context) = context_guard.as_ref() {
+            *context.lua_state.lock().unwrap() = Some(lua_state);
+            pending_commands = Some((dequeue_commands(context), context.game_fns, context.config.print));
+
             let hkbself_ptr = (context.game_fns.lua_gethkbself_fn)(lua_state);
             let behavior_context = (context.game_fns.get_hkbcontext_fn)(lua_state, hkbself_ptr);
 
@@ -167,40 +802,75 @@ unsafe fn hkbfireevent_detour(lua_state: usize) -> usize {
             let hkbcharacter_ptr = *(behavior_context as *const usize);
 
             if hkbcharacter_ptr != 0 {
-                // Name is an attribute of hkbCharacter at 0x40
-                let string_and_flag = *((hkbcharacter_ptr + 0x40) as *const usize);
+                // Name is a hkStringPtr attribute of hkbCharacter
+                let string_and_flag = *((hkbcharacter_ptr
+                    + context.game_fns.hkbcharacter_name_offset)
+                    as *const usize);
 
                 // Make sure the pointer is in userspace
                 if string_and_flag > 0x10000000000 {
                     // The stored string is a hkStringPtr, which stores a flag in the
                     // first byte. Usually 0, but just in case.
-                    let actual_string_ptr = (string_and_flag & !1) as *const i8;
+                    let actual_string_ptr =
+                        (string_and_flag & !context.game_fns.hkstringptr_flag_mask) as *const i8;
                     let character_id = CStr::from_ptr(actual_string_ptr).to_str();
 
-                    // Enemies are usually named something like c4080_1234, where 1234
-                    // is probably their model variation
-                    if character_id.is_ok()
-                        && (context.config.chr.is_empty()
-                            || character_id.unwrap().starts_with(context.config.chr.as_str()))
-                    {
+                    if let Ok(character_id) = character_id {
                         let lua_str_ptr = (context.game_fns.lua_getstring_fn)(lua_state, 1, 0);
                         let event_str =
                             CStr::from_ptr(lua_str_ptr as *const i8).to_str().unwrap();
-                        let data_str = format!("{}:{}", character_id.unwrap(), event_str);
+                        let data_str = format!("{}:{}", character_id, event_str);
+                        event_key = Some(data_str.clone());
 
-                        if context.config.print {
-                            println!("{}", data_str);
+                        // Enemies are usually named something like c4080_1234, where
+                        // 1234 is probably their model variation
+                        if context.config.chr.is_empty()
+                            || character_id.starts_with(context.config.chr.as_str())
+                        {
+                            context.publish("", character_id, event_str);
                         }
-
-                        let remote = format!("127.0.0.1:{}", context.config.port);
-                        let _ = context.sock.send_to(data_str.as_bytes(), &remote);
                     }
                 }
             }
         }
     } // lock released
 
-    HkbFireEventHook.call(lua_state)
+    if let Some((commands, game_fns, print)) = pending_commands {
+        for command in commands {
+            run_command(&game_fns, print, lua_state, command);
+        }
+    }
+
+    let depth = FIRE_EVENT_DEPTH.with(|d| {
+        let depth = d.get();
+        d.set(depth + 1);
+        depth
+    });
+
+    let start = Instant::now();
+    let result = HkbFireEventHook.call(lua_state);
+    let elapsed = start.elapsed();
+
+    FIRE_EVENT_DEPTH.with(|d| d.set(depth));
+
+    if depth == 0 {
+        if let Some(event_key) = event_key {
+            let context_guard = HOOK_CONTEXT.lock().unwrap();
+            if let Some(context) = context_guard.as_ref() {
+                if context.config.stats_interval > 0 {
+                    context
+                        .event_stats
+                        .lock()
+                        .unwrap()
+                        .entry(event_key)
+                        .or_default()
+                        .record(elapsed);
+                }
+            }
+        }
+    }
+
+    result
 }
 
 fn get_dll_dir_path() -> Option<PathBuf> {
@@ -255,16 +925,19 @@ pub unsafe extern "system" fn DllMain(
                 port: 27072,
                 chr: "c0000".to_string(),
                 print: false,
+                trace: None,
+                trace_count: default_trace_count(),
+                stats_interval: 0,
+                stats_reset: false,
+                encrypt: false,
+                key: String::new(),
+                format: default_format(),
+                lua_bindings: default_lua_bindings(),
             })
         };
 
         let sock = UdpSocket::bind("127.0.0.1:0").expect("Failed to open socket");
 
-        let _ = sock.send_to(
-            "[hkb_event_listener] I'm alive!".as_bytes(),
-            &format!("127.0.0.1:{}", config.port)
-        );
-
         println!(
             "[hkb_event_listener] will publish events to 127.0.0.1:{}",
             config.port
@@ -273,19 +946,69 @@ pub unsafe extern "system" fn DllMain(
         unsafe {
             let program = Program::current();
 
-            // Resolve all game function addresses from RVAs
-            let game_fns = GameFunctions::resolve(&program);
+            // Pick the RVA/struct-offset profile matching the running game
+            // version, falling back to the built-in 1.16.1 constants (with a
+            // warning published once the socket/context exist) if
+            // offsets.yaml is missing or has no matching entry.
+            let detected_version = offsets::detect_game_version(&program);
+            let (profile, offsets_warning) =
+                offsets::resolve_profile(get_dll_dir_path().as_deref(), detected_version.as_deref());
+
+            // Resolve all game function addresses from the chosen profile's RVAs
+            let game_fns = GameFunctions::resolve(&program, &profile);
 
             // Clone the socket for the hook context
             let sock_clone = sock.try_clone().expect("Failed to clone socket");
-            
+
+            // A second clone is handed to the command receiver thread so it
+            // can recv_from independently of the publishing side.
+            let sock_commands = sock.try_clone().expect("Failed to clone socket");
+
+            let stats_interval = config.stats_interval;
+            let stats_reset = config.stats_reset;
+
+            let encrypt_key = if config.encrypt {
+                let key = decode_hex_key(&config.key);
+                if key.is_none() {
+                    eprintln!(
+                        "[hkb_event_listener] encrypt is enabled but `key` is not 64 hex chars; falling back to plaintext"
+                    );
+                }
+                key
+            } else {
+                None
+            };
+
             // Initialize global hook context shared by all detours
             *HOOK_CONTEXT.lock().unwrap() = Some(HookContext {
                 sock: sock_clone,
                 config,
                 game_fns,
+                commands: Mutex::new(VecDeque::new()),
+                lua_state: Mutex::new(None),
+                event_stats: Mutex::new(HashMap::new()),
+                encrypt_key,
+                seq_counter: AtomicU64::new(0),
             });
 
+            if let Some(context) = HOOK_CONTEXT.lock().unwrap().as_ref() {
+                context.publish("heartbeat", "", "[hkb_event_listener] I'm alive!");
+                if let Some(warning) = &offsets_warning {
+                    eprintln!("[hkb_event_listener] {}", warning);
+                    context.publish("error", "", warning);
+                }
+            }
+
+            // Listen for `fire`/`lua` control messages on the same socket.
+            // The receiver thread only enqueues commands; it never touches
+            // the game or the Lua state directly.
+            spawn_command_receiver(sock_commands);
+
+            // Periodically publish per-event profiling stats, if enabled.
+            if stats_interval > 0 {
+                spawn_stats_publisher(stats_interval, stats_reset);
+            }
+
             // Install our hook for PushHksGlobals1
             if let Err(e) = 
                 PushHksGlobals1Hook.initialize(
@@ -0,0 +1,145 @@
+//! Version-keyed RVA/struct-offset profiles.
+//!
+//! Every address and struct offset the DLL pokes into the game is specific
+//! to a single game build. Instead of hardcoding them, they are described in
+//! an external `offsets.yaml` file keyed by the detected game version (see
+//! [`detect_game_version`]), following the same approach DFHack uses to
+//! describe binary layouts per game release. A profile matching the
+//! built-in 1.16.1 constants is always available as a fallback so the DLL
+//! still loads (against 1.16.1) if the file is missing or has no matching
+//! entry.
+
+use pelite::pe64::Pe;
+use serde::Deserialize;
+use std::path::Path;
+
+use shared::program::Program;
+
+/// One version's worth of RVAs and struct offsets.
+#[derive(Deserialize, Debug, Clone)]
+pub struct OffsetProfile {
+    pub version: String,
+    pub pushhksglobals1: u32,
+    pub hks_addnamedcclosure: u32,
+    pub hkbfireeventhks: u32,
+    pub lua_getstring: u32,
+    pub lua_gethkbself: u32,
+    pub lua_gethavokstruct: u32,
+    pub lua_pushstring: u32,
+    pub lual_loadstring: u32,
+    pub lua_pcall: u32,
+    pub lua_sethook: u32,
+    pub lua_getinfo: u32,
+    /// Offset of the `hkStringPtr` character name field on `hkbCharacter`.
+    pub hkbcharacter_name_offset: usize,
+    /// Low-bit flag mask stored in a `hkStringPtr`'s pointer field.
+    pub hkstringptr_flag_mask: usize,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct OffsetsFile {
+    profiles: Vec<OffsetProfile>,
+}
+
+/// The addresses this DLL originally shipped with, valid for game version
+/// 1.16.1. Used whenever `offsets.yaml` is missing or has no entry for the
+/// running game version.
+pub fn builtin_profile() -> OffsetProfile {
+    OffsetProfile {
+        version: "1.16.1".to_string(),
+        pushhksglobals1: 0x145ce30,
+        hks_addnamedcclosure: 0x145d9d0,
+        hkbfireeventhks: 0x145a960,
+        lua_getstring: 0x14e26c0,
+        lua_gethkbself: 0x14522e0,
+        lua_gethavokstruct: 0x1451760,
+        lua_pushstring: 0x14e2940,
+        lual_loadstring: 0x14e4a10,
+        lua_pcall: 0x14e3180,
+        lua_sethook: 0x14e3c50,
+        lua_getinfo: 0x14e3390,
+        hkbcharacter_name_offset: 0x40,
+        hkstringptr_flag_mask: 1,
+    }
+}
+
+/// Read the product version (`dwProductVersionMS`/`LS`) out of the running
+/// module's `VS_FIXEDFILEINFO` resource, formatted as `"major.minor.patch"`
+/// to match the `version` keys in `offsets.yaml` and [`builtin_profile`]
+/// (the fourth, build, component isn't part of those keys). Falls back to
+/// the PE timestamp (as `"ts:<timestamp>"`) when no version resource is
+/// present, which still uniquely identifies a build.
+pub fn detect_game_version(program: &Program) -> Option<String> {
+    if let Ok(resources) = program.resources() {
+        if let Ok(version_info) = resources.version_info() {
+            if let Some(fixed) = version_info.fixed() {
+                let ms = fixed.dwProductVersionMS;
+                let ls = fixed.dwProductVersionLS;
+                return Some(format!("{}.{}.{}", ms >> 16, ms & 0xFFFF, ls >> 16));
+            }
+        }
+    }
+
+    let timestamp = program.nt_headers().FileHeader.TimeDateStamp;
+    if timestamp != 0 {
+        Some(format!("ts:{}", timestamp))
+    } else {
+        None
+    }
+}
+
+/// Load `offsets.yaml` from `dll_dir` (if it exists and parses) and pick the
+/// profile matching `detected_version`. Returns the resolved profile along
+/// with a human-readable warning when it had to fall back to
+/// [`builtin_profile`] - `None` when a profile was matched cleanly.
+pub fn resolve_profile(
+    dll_dir: Option<&Path>,
+    detected_version: Option<&str>,
+) -> (OffsetProfile, Option<String>) {
+    let offsets_path = dll_dir
+        .map(|dir| dir.join("offsets.yaml"))
+        .unwrap_or_else(|| Path::new("offsets.yaml").to_path_buf());
+
+    let profiles = match std::fs::read_to_string(&offsets_path) {
+        Ok(contents) => match serde_yaml::from_str::<OffsetsFile>(&contents) {
+            Ok(file) => file.profiles,
+            Err(e) => {
+                return (
+                    builtin_profile(),
+                    Some(format!(
+                        "failed to parse {}: {e}; using built-in 1.16.1 offsets",
+                        offsets_path.display()
+                    )),
+                );
+            }
+        },
+        Err(_) => {
+            return (
+                builtin_profile(),
+                Some(format!(
+                    "{} not found; using built-in 1.16.1 offsets",
+                    offsets_path.display()
+                )),
+            );
+        }
+    };
+
+    match detected_version {
+        Some(version) => match profiles.into_iter().find(|p| p.version == version) {
+            Some(profile) => (profile, None),
+            None => (
+                builtin_profile(),
+                Some(format!(
+                    "no offsets.yaml profile for detected game version {version}; using built-in 1.16.1 offsets"
+                )),
+            ),
+        },
+        None => (
+            builtin_profile(),
+            Some(
+                "could not detect the running game version; using built-in 1.16.1 offsets"
+                    .to_string(),
+            ),
+        ),
+    }
+}